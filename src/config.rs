@@ -0,0 +1,149 @@
+//! User-editable settings: typing method, auto-toggle rules and the macro
+//! table. Persisted as JSON under the platform config directory.
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::hotkey::{Action, Binding, Hotkey, Keymap, RawKey, RawKeyBinding};
+use crate::rewriter::{EventRewriter, RewriteRule};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TypingMethod {
+    Telex,
+    VNI,
+}
+
+impl Default for TypingMethod {
+    fn default() -> Self {
+        Self::Telex
+    }
+}
+
+/// Which keys Telex/VNI rules are matched against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TypingLayout {
+    /// Match against whatever character the active system keyboard layout
+    /// produces. Simple, but Telex/VNI rules move around (or break) when a
+    /// non-US layout like Dvorak or AZERTY is active.
+    FollowActiveLayout,
+    /// Always match against standard US-QWERTY key positions, regardless of
+    /// the active system layout.
+    UsQwertyPositions,
+}
+
+impl Default for TypingLayout {
+    fn default() -> Self {
+        Self::FollowActiveLayout
+    }
+}
+
+/// One entry of the user-editable keymap, as stored on disk. `chord` round-trips
+/// through [`Hotkey`]'s `FromStr`/`Display` impls (e.g. `"CTRL+SHIFT+Z"`);
+/// entries that fail to parse are logged and skipped rather than rejecting
+/// the whole config.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BindingConfig {
+    pub chord: String,
+    pub action: Action,
+}
+
+/// One entry of the user-editable keymap bound to a named [`RawKey`] (e.g.
+/// the Globe key) instead of a chord string.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawKeyBindingConfig {
+    pub key: RawKey,
+    pub action: Action,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub typing_method: TypingMethod,
+    pub typing_layout: TypingLayout,
+    pub bindings: Vec<BindingConfig>,
+    pub raw_key_bindings: Vec<RawKeyBindingConfig>,
+    pub rewrite_rules: Vec<RewriteRule>,
+    pub sticky_modifiers_enabled: bool,
+    pub auto_toggle_enabled: bool,
+    pub allowed_words: Vec<String>,
+    pub macro_table: Vec<(String, String)>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            typing_method: TypingMethod::default(),
+            typing_layout: TypingLayout::default(),
+            bindings: vec![BindingConfig {
+                // Released with no other key held: GõKey's traditional
+                // default toggle.
+                chord: "CTRL+SHIFT".to_string(),
+                action: Action::ToggleVietnamese,
+            }],
+            raw_key_bindings: vec![RawKeyBindingConfig {
+                // GõKey's other traditional default toggle.
+                key: RawKey::Globe,
+                action: Action::ToggleVietnamese,
+            }],
+            rewrite_rules: Vec::new(),
+            sticky_modifiers_enabled: false,
+            auto_toggle_enabled: true,
+            allowed_words: Vec::new(),
+            macro_table: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Self {
+        Self::config_path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let Some(path) = Self::config_path() else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let contents = serde_json::to_string_pretty(self).unwrap_or_default();
+        std::fs::write(path, contents)
+    }
+
+    fn config_path() -> Option<std::path::PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("GoxKey").join("config.json"))
+    }
+
+    /// Build the resolved [`Keymap`] for these bindings, skipping (and
+    /// logging) any chord that fails to parse.
+    pub fn keymap(&self) -> Keymap {
+        let bindings = self
+            .bindings
+            .iter()
+            .filter_map(|binding| match binding.chord.parse::<Hotkey>() {
+                Ok(parsed) => Some(Binding {
+                    hotkey: parsed,
+                    action: binding.action,
+                }),
+                Err(err) => {
+                    warn!("Ignoring unparsable keymap chord {:?}: {err}", binding.chord);
+                    None
+                }
+            })
+            .collect();
+        let raw_key_bindings = self
+            .raw_key_bindings
+            .iter()
+            .map(|binding| RawKeyBinding { key: binding.key, action: binding.action })
+            .collect();
+        Keymap::new(bindings, raw_key_bindings)
+    }
+
+    /// Build the [`EventRewriter`] that should run before any IME logic.
+    pub fn event_rewriter(&self) -> EventRewriter {
+        EventRewriter::new(self.rewrite_rules.clone(), self.sticky_modifiers_enabled)
+    }
+}
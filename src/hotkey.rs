@@ -0,0 +1,333 @@
+//! Keyboard chords and the keymap that maps them to actions.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::platform::{self, KeyModifier};
+
+/// A modifier chord, optionally combined with a single character key, e.g.
+/// "Control+Shift" or "Control+Shift+Z".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hotkey {
+    pub modifiers: KeyModifier,
+    pub key: Option<char>,
+}
+
+/// A chord string that couldn't be parsed, e.g. an unknown modifier name or
+/// more than one non-modifier token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseHotkeyError(String);
+
+impl fmt::Display for ParseHotkeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid hotkey token {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseHotkeyError {}
+
+impl FromStr for Hotkey {
+    type Err = ParseHotkeyError;
+
+    /// Parses a chord written as modifier tokens and an optional key joined
+    /// by `+`, e.g. `"CTRL+SHIFT"` or `"Ctrl+Shift+Z"`. Case-insensitive and
+    /// tolerant of modifier order.
+    fn from_str(chord: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = KeyModifier::MODIFIER_NONE;
+        let mut key = None;
+        let mut saw_token = false;
+        for token in chord.split('+') {
+            let token = token.trim();
+            if token.is_empty() {
+                return Err(ParseHotkeyError(chord.to_string()));
+            }
+            saw_token = true;
+            match token.to_ascii_uppercase().as_str() {
+                "CTRL" | "CONTROL" => modifiers.insert(KeyModifier::CONTROL),
+                "SHIFT" => modifiers.insert(KeyModifier::SHIFT),
+                "ALT" => modifiers.insert(KeyModifier::ALT),
+                "META" | "SUPER" | "CMD" => modifiers.insert(KeyModifier::SUPER),
+                single if single.chars().count() == 1 && key.is_none() => {
+                    key = token.chars().next().map(|c| c.to_ascii_uppercase());
+                }
+                _ => return Err(ParseHotkeyError(token.to_string())),
+            }
+        }
+        if !saw_token {
+            return Err(ParseHotkeyError(chord.to_string()));
+        }
+        Ok(Hotkey::new(modifiers, key))
+    }
+}
+
+impl fmt::Display for Hotkey {
+    /// Renders the canonical form a chord round-trips through, e.g.
+    /// `CTRL+SHIFT+Z`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut tokens = Vec::new();
+        if self.modifiers.is_control() {
+            tokens.push("CTRL".to_string());
+        }
+        if self.modifiers.is_shift() {
+            tokens.push("SHIFT".to_string());
+        }
+        if self.modifiers.is_alt() {
+            tokens.push("ALT".to_string());
+        }
+        if self.modifiers.is_super() {
+            tokens.push("META".to_string());
+        }
+        if let Some(key) = self.key {
+            tokens.push(key.to_ascii_uppercase().to_string());
+        }
+        write!(f, "{}", tokens.join("+"))
+    }
+}
+
+impl Hotkey {
+    pub fn new(modifiers: KeyModifier, key: Option<char>) -> Self {
+        Self { modifiers, key }
+    }
+
+    /// Whether the currently held `modifiers` (and, if this hotkey requires
+    /// one, `key`) match this chord.
+    pub fn is_match(&self, modifiers: KeyModifier, key: Option<char>) -> bool {
+        if self.modifiers != modifiers {
+            return false;
+        }
+        match self.key {
+            Some(expected) => key.map(|k| k.eq_ignore_ascii_case(&expected)).unwrap_or(false),
+            None => true,
+        }
+    }
+}
+
+impl Default for Hotkey {
+    fn default() -> Self {
+        // Control+Shift, released with no other key pressed, is GõKey's
+        // traditional default toggle.
+        Self::new(KeyModifier::CONTROL | KeyModifier::SHIFT, None)
+    }
+}
+
+/// Something a bound chord can do. Mirrors the handful of operations
+/// `event_handler` already knew how to perform, just dispatched through a
+/// table instead of being hardwired to one hotkey.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Action {
+    ToggleVietnamese,
+    TemporarilyDisable,
+    RestoreWord,
+    CommitRaw,
+    SwitchTypingMethod,
+}
+
+/// One entry of a [`Keymap`]: a chord and the action it should run.
+#[derive(Debug, Clone, Copy)]
+pub struct Binding {
+    pub hotkey: Hotkey,
+    pub action: Action,
+}
+
+/// A named key with no character representation of its own, so it can't be
+/// expressed as a [`Hotkey`] (whose `key` is a `char`) — the numeric raw
+/// keycode it maps to is platform-specific, so the config only ever stores
+/// the name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum RawKey {
+    /// The "fn"/Globe key found on some keyboards.
+    Globe,
+}
+
+impl RawKey {
+    fn code(self) -> u16 {
+        match self {
+            RawKey::Globe => platform::RAW_KEY_GLOBE,
+        }
+    }
+}
+
+/// One entry of a [`Keymap`] bound to a [`RawKey`] instead of a [`Hotkey`].
+#[derive(Debug, Clone, Copy)]
+pub struct RawKeyBinding {
+    pub key: RawKey,
+    pub action: Action,
+}
+
+/// The resolved set of bindings a user can trigger. `event_handler` asks
+/// this for an [`Action`] instead of comparing against one inline hotkey.
+#[derive(Debug, Clone, Default)]
+pub struct Keymap {
+    bindings: Vec<Binding>,
+    raw_key_bindings: Vec<RawKeyBinding>,
+}
+
+impl Keymap {
+    pub fn new(bindings: Vec<Binding>, raw_key_bindings: Vec<RawKeyBinding>) -> Self {
+        Self { bindings, raw_key_bindings }
+    }
+
+    /// Resolve a modifier-chord-only binding, i.e. one with no key of its
+    /// own. These fire when the chord's modifiers are released, since
+    /// otherwise they'd hijack every keystroke typed while held.
+    pub fn resolve_modifier_chord(&self, modifiers: KeyModifier) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.hotkey.key.is_none() && binding.hotkey.is_match(modifiers, None))
+            .map(|binding| binding.action)
+    }
+
+    /// Resolve a binding that requires a specific key, matched immediately
+    /// on key-down.
+    pub fn resolve_key(&self, modifiers: KeyModifier, key: char) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.hotkey.key.is_some() && binding.hotkey.is_match(modifiers, Some(key)))
+            .map(|binding| binding.action)
+    }
+
+    /// Resolve a binding on a [`RawKey`] such as the Globe key, matched
+    /// immediately on key-down regardless of held modifiers.
+    pub fn resolve_raw_key(&self, raw_keycode: u16) -> Option<Action> {
+        self.raw_key_bindings
+            .iter()
+            .find(|binding| binding.key.code() == raw_keycode)
+            .map(|binding| binding.action)
+    }
+}
+
+impl Default for Action {
+    fn default() -> Self {
+        Self::ToggleVietnamese
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_modifier_only_chord() {
+        let hotkey: Hotkey = "CTRL+SHIFT".parse().unwrap();
+        assert_eq!(hotkey.modifiers, KeyModifier::CONTROL | KeyModifier::SHIFT);
+        assert_eq!(hotkey.key, None);
+    }
+
+    #[test]
+    fn parses_chord_with_key() {
+        let hotkey: Hotkey = "Ctrl+Shift+Z".parse().unwrap();
+        assert_eq!(hotkey.modifiers, KeyModifier::CONTROL | KeyModifier::SHIFT);
+        assert_eq!(hotkey.key, Some('Z'));
+    }
+
+    #[test]
+    fn parsing_is_case_insensitive() {
+        let upper: Hotkey = "CTRL+ALT+K".parse().unwrap();
+        let lower: Hotkey = "ctrl+alt+k".parse().unwrap();
+        assert_eq!(upper, lower);
+    }
+
+    #[test]
+    fn parsing_tolerates_modifier_order() {
+        let a: Hotkey = "CTRL+SHIFT+ALT".parse().unwrap();
+        let b: Hotkey = "ALT+CTRL+SHIFT".parse().unwrap();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn accepts_modifier_aliases() {
+        let meta: Hotkey = "META".parse().unwrap();
+        let cmd: Hotkey = "CMD".parse().unwrap();
+        let super_: Hotkey = "SUPER".parse().unwrap();
+        assert_eq!(meta.modifiers, KeyModifier::SUPER);
+        assert_eq!(meta, cmd);
+        assert_eq!(meta, super_);
+    }
+
+    #[test]
+    fn rejects_unknown_token() {
+        assert!("CTRL+FROB".parse::<Hotkey>().is_err());
+    }
+
+    #[test]
+    fn rejects_more_than_one_key() {
+        assert!("CTRL+A+B".parse::<Hotkey>().is_err());
+    }
+
+    #[test]
+    fn rejects_empty_token() {
+        assert!("CTRL++Z".parse::<Hotkey>().is_err());
+        assert!("".parse::<Hotkey>().is_err());
+    }
+
+    #[test]
+    fn display_round_trips_through_from_str() {
+        for chord in ["CTRL+SHIFT", "CTRL+SHIFT+Z", "ALT+META+Q"] {
+            let hotkey: Hotkey = chord.parse().unwrap();
+            let rendered = hotkey.to_string();
+            let reparsed: Hotkey = rendered.parse().unwrap();
+            assert_eq!(hotkey, reparsed);
+        }
+    }
+
+    #[test]
+    fn display_canonicalizes_modifier_order() {
+        let hotkey: Hotkey = "META+ALT+SHIFT+CTRL".parse().unwrap();
+        assert_eq!(hotkey.to_string(), "CTRL+SHIFT+ALT+META");
+    }
+
+    #[test]
+    fn is_match_requires_exact_modifiers() {
+        let hotkey = Hotkey::new(KeyModifier::CONTROL, Some('z'));
+        assert!(hotkey.is_match(KeyModifier::CONTROL, Some('Z')));
+        assert!(!hotkey.is_match(KeyModifier::CONTROL | KeyModifier::SHIFT, Some('z')));
+        assert!(!hotkey.is_match(KeyModifier::CONTROL, Some('x')));
+        assert!(!hotkey.is_match(KeyModifier::CONTROL, None));
+    }
+
+    #[test]
+    fn is_match_modifier_only_ignores_key() {
+        let hotkey = Hotkey::new(KeyModifier::CONTROL | KeyModifier::SHIFT, None);
+        assert!(hotkey.is_match(KeyModifier::CONTROL | KeyModifier::SHIFT, None));
+        assert!(hotkey.is_match(KeyModifier::CONTROL | KeyModifier::SHIFT, Some('a')));
+        assert!(!hotkey.is_match(KeyModifier::CONTROL, None));
+    }
+
+    #[test]
+    fn keymap_resolves_key_and_modifier_chord_bindings() {
+        let keymap = Keymap::new(
+            vec![
+                Binding {
+                    hotkey: Hotkey::new(KeyModifier::CONTROL | KeyModifier::SHIFT, None),
+                    action: Action::ToggleVietnamese,
+                },
+                Binding {
+                    hotkey: Hotkey::new(KeyModifier::CONTROL, Some('r')),
+                    action: Action::RestoreWord,
+                },
+            ],
+            Vec::new(),
+        );
+
+        assert_eq!(
+            keymap.resolve_modifier_chord(KeyModifier::CONTROL | KeyModifier::SHIFT),
+            Some(Action::ToggleVietnamese)
+        );
+        assert_eq!(keymap.resolve_key(KeyModifier::CONTROL, 'r'), Some(Action::RestoreWord));
+        assert_eq!(keymap.resolve_key(KeyModifier::CONTROL, 'x'), None);
+    }
+
+    #[test]
+    fn keymap_resolves_raw_key_bindings() {
+        let keymap = Keymap::new(
+            Vec::new(),
+            vec![RawKeyBinding { key: RawKey::Globe, action: Action::ToggleVietnamese }],
+        );
+
+        assert_eq!(
+            keymap.resolve_raw_key(RawKey::Globe.code()),
+            Some(Action::ToggleVietnamese)
+        );
+        assert_eq!(keymap.resolve_raw_key(RawKey::Globe.code().wrapping_add(1)), None);
+    }
+}
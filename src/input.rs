@@ -0,0 +1,197 @@
+//! The Vietnamese typing state machine: tracks the word currently being
+//! typed, runs it through the `vi` transformation engine, and decides what
+//! (if anything) should be sent to the focused app.
+
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use vi::TransformResult;
+
+use crate::config::{Config, TypingLayout, TypingMethod};
+use crate::hotkey::Keymap;
+use crate::platform::{self, KeyModifier, PhysicalKey};
+
+pub static INPUT_STATE: Lazy<Mutex<InputState>> = Lazy::new(|| Mutex::new(InputState::new()));
+pub static HOTKEY_MODIFIERS: Mutex<KeyModifier> = Mutex::new(KeyModifier::MODIFIER_NONE);
+pub static HOTKEY_MATCHING: Mutex<bool> = Mutex::new(false);
+pub static HOTKEY_MATCHING_CIRCUIT_BREAK: Mutex<bool> = Mutex::new(false);
+
+/// Called once at startup. Does nothing: no layout mapping is cached, since
+/// the event tap reads the character of each [`PressedKey::Char`] from the
+/// event itself.
+pub fn rebuild_keyboard_layout_map() {}
+
+pub struct InputState {
+    typing_buffer: String,
+    displaying_word: String,
+    enabled: bool,
+    temporarily_disabled: bool,
+    previous_modifiers: KeyModifier,
+    previous_word_stopped_tracking: bool,
+    active_app: Option<String>,
+    config: Config,
+    keymap: Keymap,
+}
+
+impl InputState {
+    fn new() -> Self {
+        let config = Config::load();
+        let keymap = config.keymap();
+        Self {
+            typing_buffer: String::new(),
+            displaying_word: String::new(),
+            enabled: true,
+            temporarily_disabled: false,
+            previous_modifiers: KeyModifier::MODIFIER_NONE,
+            previous_word_stopped_tracking: false,
+            active_app: None,
+            config,
+            keymap,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled && !self.temporarily_disabled
+    }
+
+    pub fn is_tracking(&self) -> bool {
+        self.is_enabled()
+    }
+
+    pub fn toggle_vietnamese(&mut self) {
+        self.enabled = !self.enabled;
+        self.new_word();
+    }
+
+    pub fn set_temporary_disabled(&mut self) {
+        self.temporarily_disabled = true;
+    }
+
+    pub fn is_auto_toggle_enabled(&self) -> bool {
+        self.config.auto_toggle_enabled
+    }
+
+    /// Records the frontmost app. Returns `Some(())` if it changed since the
+    /// last check. The enabled state is left as it is; no per-app rule is
+    /// applied here.
+    pub fn update_active_app(&mut self) -> Option<()> {
+        let frontmost = crate::platform::frontmost_app_bundle_id();
+        if frontmost == self.active_app {
+            return None;
+        }
+        self.active_app = frontmost;
+        Some(())
+    }
+
+    /// The resolved keymap, parsed once from the config's chord strings at
+    /// startup and cloned cheaply from here on — `event_handler` calls this
+    /// on every single key event from the `CGEventTap` thread, which has
+    /// little tolerance for latency before the OS disables the tap.
+    pub fn get_keymap(&self) -> Keymap {
+        self.keymap.clone()
+    }
+
+    pub fn switch_typing_method(&mut self) {
+        self.config.typing_method = match self.config.typing_method {
+            TypingMethod::Telex => TypingMethod::VNI,
+            TypingMethod::VNI => TypingMethod::Telex,
+        };
+        self.new_word();
+    }
+
+    /// The character typing rules (hotkey matching, Telex/VNI, word
+    /// boundaries) should be matched against for a keystroke reported as
+    /// `physical`/`text`. Under "follow active layout" this is just `text`;
+    /// under "US-QWERTY positions" it's whatever that physical key produces
+    /// on a standard US layout, regardless of what's actually active.
+    pub fn effective_char(&self, physical: PhysicalKey, text: char) -> char {
+        match self.config.typing_layout {
+            TypingLayout::FollowActiveLayout => text,
+            TypingLayout::UsQwertyPositions => {
+                platform::us_qwerty_char(physical).unwrap_or(text)
+            }
+        }
+    }
+
+    pub fn get_previous_modifiers(&self) -> KeyModifier {
+        self.previous_modifiers
+    }
+
+    pub fn get_typing_buffer(&self) -> &str {
+        &self.typing_buffer
+    }
+
+    pub fn get_displaying_word(&self) -> &str {
+        &self.displaying_word
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.typing_buffer.push(c);
+    }
+
+    pub fn pop(&mut self) {
+        self.typing_buffer.pop();
+        self.displaying_word.pop();
+    }
+
+    pub fn new_word(&mut self) {
+        self.temporarily_disabled = false;
+        self.typing_buffer.clear();
+        self.displaying_word.clear();
+    }
+
+    pub fn stop_tracking(&mut self) {
+        self.previous_word_stopped_tracking = true;
+    }
+
+    pub fn previous_word_is_stop_tracking_words(&self) -> bool {
+        self.previous_word_stopped_tracking
+    }
+
+    pub fn clear_previous_word(&mut self) {
+        self.previous_word_stopped_tracking = false;
+    }
+
+    pub fn is_allowed_word(&self, word: &str) -> bool {
+        self.config
+            .allowed_words
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(word))
+    }
+
+    pub fn get_macro_target(&self) -> Option<&String> {
+        self.config
+            .macro_table
+            .iter()
+            .find(|(shortcut, _)| shortcut == &self.displaying_word)
+            .map(|(_, target)| target)
+    }
+
+    /// Runs the typing buffer through the Vietnamese transformation engine
+    /// and reports what changed.
+    pub fn transform_keys(&mut self) -> Result<(String, TransformResult), ()> {
+        let method = match self.config.typing_method {
+            TypingMethod::Telex => &vi::TELEX,
+            TypingMethod::VNI => &vi::VNI,
+        };
+        let mut output = String::new();
+        let result = vi::transform_buffer(method, self.typing_buffer.chars(), &mut output);
+        Ok((output, result))
+    }
+
+    /// Whether `output` differs from what's currently shown and so is worth
+    /// sending on to the focused app.
+    pub fn should_send_keyboard_event(&self, output: &str) -> bool {
+        output != self.displaying_word
+    }
+
+    /// How many characters of the currently displayed word need to be
+    /// removed before sending replacement text.
+    pub fn get_backspace_count(&self, is_delete: bool) -> usize {
+        self.displaying_word.chars().count() + usize::from(is_delete)
+    }
+
+    pub fn replace(&mut self, new_word: String) {
+        self.displaying_word = new_word;
+    }
+}
@@ -2,28 +2,32 @@ mod config;
 mod hotkey;
 mod input;
 mod platform;
+mod rewriter;
 mod scripting;
 mod ui;
 
 use std::thread;
 
 use druid::{AppLauncher, ExtEventSink, Target, WindowDesc};
+use hotkey::Action;
 use input::{rebuild_keyboard_layout_map, HOTKEY_MATCHING_CIRCUIT_BREAK, INPUT_STATE};
 use log::debug;
 use once_cell::sync::OnceCell;
 use platform::{
     add_app_change_callback, ensure_accessibility_permission, run_event_listener, send_backspace,
-    send_string, EventTapType, Handle, KeyModifier, PressedKey, KEY_DELETE, KEY_ENTER, KEY_ESCAPE,
-    KEY_SPACE, KEY_TAB, RAW_KEY_GLOBE,
+    send_string, Composition, EventTapType, Handle, KeyModifier, PressedKey, KEY_DELETE,
+    KEY_ENTER, KEY_ESCAPE, KEY_SPACE, KEY_TAB,
 };
 
 use crate::{
     input::{HOTKEY_MATCHING, HOTKEY_MODIFIERS},
     platform::{RAW_ARROW_DOWN, RAW_ARROW_LEFT, RAW_ARROW_RIGHT, RAW_ARROW_UP},
 };
+use rewriter::EventRewriter;
 use ui::{UIDataAdapter, UPDATE_UI};
 
 static UI_EVENT_SINK: OnceCell<ExtEventSink> = OnceCell::new();
+static EVENT_REWRITER: OnceCell<EventRewriter> = OnceCell::new();
 const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 fn do_transform_keys(handle: Handle, is_delete: bool) -> bool {
@@ -31,19 +35,18 @@ fn do_transform_keys(handle: Handle, is_delete: bool) -> bool {
     if let Ok((output, transform_result)) = input_state.transform_keys() {
         debug!("Transformed: {:?}", output);
         if input_state.should_send_keyboard_event(&output) || is_delete {
-            // This is a workaround for Firefox, where macOS's Accessibility API cannot work.
-            // We cannot get the selected text in the address bar, so we will go with another
-            // hacky way: Always send a space and delete it immediately. This will dismiss the
-            // current pre-selected URL and fix the double character issue.
-            if input_state.should_dismiss_selection_if_needed() {
-                _ = send_string(handle, " ");
-                _ = send_backspace(handle, 1);
+            // Show the transformed word as marked (preedit) text owned by the
+            // IME, so the focused app's own selection/undo handling never
+            // sees the intermediate keystrokes. Only apps whose focused view
+            // doesn't implement NSTextInputClient need the backspace-and-
+            // resend fallback.
+            let cursor = output.chars().count();
+            if !Composition::set_marked_text(handle, &output, cursor) {
+                let backspace_count = input_state.get_backspace_count(is_delete);
+                debug!("Backspace count: {}", backspace_count);
+                _ = send_backspace(handle, backspace_count);
+                _ = send_string(handle, &output);
             }
-
-            let backspace_count = input_state.get_backspace_count(is_delete);
-            debug!("Backspace count: {}", backspace_count);
-            _ = send_backspace(handle, backspace_count);
-            _ = send_string(handle, &output);
             debug!("Sent: {:?}", output);
             input_state.replace(output);
             if transform_result.letter_modification_removed
@@ -59,26 +62,83 @@ fn do_transform_keys(handle: Handle, is_delete: bool) -> bool {
 
 fn do_restore_word(handle: Handle) {
     let mut input_state = INPUT_STATE.lock().unwrap();
-    let backspace_count = input_state.get_backspace_count(true);
-    debug!("Backspace count: {}", backspace_count);
-    _ = send_backspace(handle, backspace_count);
     let typing_buffer = input_state.get_typing_buffer().to_string();
-    _ = send_string(handle, &typing_buffer);
+    if !Composition::commit(handle, &typing_buffer) {
+        let backspace_count = input_state.get_backspace_count(true);
+        debug!("Backspace count: {}", backspace_count);
+        _ = send_backspace(handle, backspace_count);
+        _ = send_string(handle, &typing_buffer);
+    }
     debug!("Sent: {:?}", typing_buffer);
     input_state.replace(typing_buffer);
 }
 
 fn do_macro_replace(handle: Handle, target: &String) {
     let mut input_state = INPUT_STATE.lock().unwrap();
-    let backspace_count = input_state.get_backspace_count(true);
-    debug!("Backspace count: {}", backspace_count);
-    _ = send_backspace(handle, backspace_count);
-    _ = send_string(handle, target);
+    if !Composition::commit(handle, target) {
+        let backspace_count = input_state.get_backspace_count(true);
+        debug!("Backspace count: {}", backspace_count);
+        _ = send_backspace(handle, backspace_count);
+        _ = send_string(handle, target);
+    }
     debug!("Sent: {:?}", target);
     input_state.replace(target.to_owned());
 }
 
-fn toggle_vietnamese() {
+/// Ends composition for the current word by committing whatever is
+/// currently marked, without otherwise touching the typing state. Called on
+/// word boundaries (space/enter/tab/punctuation) once the transformed word
+/// has already been decided to be correct, so no restore or macro expansion
+/// ran.
+fn do_commit_word(handle: Handle, word: &str) {
+    if !word.is_empty() {
+        _ = Composition::commit(handle, word);
+    }
+}
+
+/// Discards whatever is currently marked without committing it to the
+/// document. Called on Escape, which should back out of a composition
+/// instead of finalizing it.
+fn do_clear_word(handle: Handle) {
+    _ = Composition::clear(handle);
+}
+
+/// Restores any in-flight word to its raw, untransformed form before an
+/// action that resets typing state outright, instead of finishing the word
+/// normally — otherwise the marked text `do_transform_keys` already pushed
+/// via `Composition::set_marked_text` is abandoned on screen, never
+/// committed or cleared.
+fn restore_in_flight_word(handle: Handle) {
+    if !INPUT_STATE.lock().unwrap().get_typing_buffer().is_empty() {
+        do_restore_word(handle);
+    }
+}
+
+/// Runs the effect of a resolved keymap [`Action`].
+fn dispatch_action(action: Action, handle: Handle) {
+    match action {
+        Action::ToggleVietnamese => toggle_vietnamese(handle),
+        Action::TemporarilyDisable => {
+            restore_in_flight_word(handle);
+            INPUT_STATE.lock().unwrap().set_temporary_disabled();
+        }
+        Action::RestoreWord => do_restore_word(handle),
+        Action::CommitRaw => {
+            let typing_buffer = INPUT_STATE.lock().unwrap().get_typing_buffer().to_string();
+            do_commit_word(handle, &typing_buffer);
+        }
+        Action::SwitchTypingMethod => {
+            restore_in_flight_word(handle);
+            INPUT_STATE.lock().unwrap().switch_typing_method();
+            if let Some(event_sink) = UI_EVENT_SINK.get() {
+                _ = event_sink.submit_command(UPDATE_UI, (), Target::Auto);
+            }
+        }
+    }
+}
+
+fn toggle_vietnamese(handle: Handle) {
+    restore_in_flight_word(handle);
     INPUT_STATE.lock().unwrap().toggle_vietnamese();
     if let Some(event_sink) = UI_EVENT_SINK.get() {
         _ = event_sink.submit_command(UPDATE_UI, (), Target::Auto);
@@ -105,25 +165,34 @@ fn event_handler(
     pressed_key: Option<PressedKey>,
     modifiers: KeyModifier,
 ) -> bool {
+    let (pressed_key, modifiers) = EVENT_REWRITER
+        .get()
+        .map(|rewriter| rewriter.rewrite(pressed_key, modifiers))
+        .unwrap_or((pressed_key, modifiers));
+
     let mut input_state = INPUT_STATE.lock().unwrap();
     let mut hotkey_modifiers = HOTKEY_MODIFIERS.lock().unwrap();
     let mut hotkey_matching = HOTKEY_MATCHING.lock().unwrap();
     let mut hotkey_matching_circuit_break = HOTKEY_MATCHING_CIRCUIT_BREAK.lock().unwrap();
     let pressed_key_code = pressed_key.and_then(|p| match p {
-        PressedKey::Char(c) => Some(c),
+        PressedKey::Char { physical, text } => Some(input_state.effective_char(physical, text)),
         _ => None,
     });
 
+    let keymap = input_state.get_keymap();
+
     if event_type == EventTapType::FlagsChanged {
         if modifiers.is_empty() {
             // Modifier keys are released
             if *hotkey_matching && !*hotkey_matching_circuit_break {
-                drop(input_state); // release lock before calling toggle_vietnamese
-                toggle_vietnamese();
-                input_state = INPUT_STATE.lock().unwrap(); // re-acquire
-                hotkey_modifiers = HOTKEY_MODIFIERS.lock().unwrap();
-                hotkey_matching = HOTKEY_MATCHING.lock().unwrap();
-                hotkey_matching_circuit_break = HOTKEY_MATCHING_CIRCUIT_BREAK.lock().unwrap();
+                if let Some(action) = keymap.resolve_modifier_chord(*hotkey_modifiers) {
+                    drop(input_state); // release lock before dispatching
+                    dispatch_action(action, handle);
+                    input_state = INPUT_STATE.lock().unwrap(); // re-acquire
+                    hotkey_modifiers = HOTKEY_MODIFIERS.lock().unwrap();
+                    hotkey_matching = HOTKEY_MATCHING.lock().unwrap();
+                    hotkey_matching_circuit_break = HOTKEY_MATCHING_CIRCUIT_BREAK.lock().unwrap();
+                }
             }
             *hotkey_modifiers = KeyModifier::MODIFIER_NONE;
             *hotkey_matching = false;
@@ -133,35 +202,48 @@ fn event_handler(
         }
     }
 
-    let is_hotkey_matched = input_state
-        .get_hotkey()
-        .is_match(*hotkey_modifiers, pressed_key_code);
+    let is_hotkey_matched = keymap.resolve_modifier_chord(*hotkey_modifiers).is_some();
     if *hotkey_matching && !is_hotkey_matched {
         *hotkey_matching_circuit_break = true;
     }
     *hotkey_matching = is_hotkey_matched;
 
+    if let Some(key) = pressed_key_code {
+        if let Some(action) = keymap.resolve_key(modifiers, key) {
+            drop(input_state);
+            dispatch_action(action, handle);
+            return true;
+        }
+    }
+
     match pressed_key {
         Some(pressed_key) => {
             match pressed_key {
                 PressedKey::Raw(raw_keycode) => {
-                    if raw_keycode == RAW_KEY_GLOBE {
+                    if let Some(action) = keymap.resolve_raw_key(raw_keycode) {
                         drop(input_state);
-                        toggle_vietnamese();
+                        dispatch_action(action, handle);
                         return true;
                     }
                     if raw_keycode == RAW_ARROW_UP || raw_keycode == RAW_ARROW_DOWN {
+                        do_commit_word(handle, input_state.get_displaying_word());
                         input_state.new_word();
                     }
                     if raw_keycode == RAW_ARROW_LEFT || raw_keycode == RAW_ARROW_RIGHT {
                         // TODO: Implement a better cursor tracking on each word here
+                        do_commit_word(handle, input_state.get_displaying_word());
                         input_state.new_word();
                     }
                 }
-                PressedKey::Char(keycode) => {
+                PressedKey::Char { physical, text } => {
+                    let keycode = input_state.effective_char(physical, text);
                     if input_state.is_enabled() {
                         match keycode {
-                            KEY_ENTER | KEY_TAB | KEY_SPACE | KEY_ESCAPE => {
+                            KEY_ESCAPE => {
+                                do_clear_word(handle);
+                                input_state.new_word();
+                            }
+                            KEY_ENTER | KEY_TAB | KEY_SPACE => {
                                 let is_valid_word = vi::validation::is_valid_word(
                                     input_state.get_displaying_word(),
                                 );
@@ -170,10 +252,12 @@ fn event_handler(
                                 let is_transformed_word = !input_state
                                     .get_typing_buffer()
                                     .eq(input_state.get_displaying_word());
+                                let mut composition_finalized = false;
                                 if is_transformed_word && !is_valid_word && !is_allowed_word {
                                     drop(input_state);
                                     do_restore_word(handle);
                                     input_state = INPUT_STATE.lock().unwrap();
+                                    composition_finalized = true;
                                 }
 
                                 if input_state.previous_word_is_stop_tracking_words() {
@@ -186,13 +270,19 @@ fn event_handler(
                                         drop(input_state);
                                         do_macro_replace(handle, &macro_target);
                                         input_state = INPUT_STATE.lock().unwrap();
+                                        composition_finalized = true;
                                     }
                                 }
 
+                                if !composition_finalized {
+                                    do_commit_word(handle, input_state.get_displaying_word());
+                                }
+
                                 input_state.new_word();
                             }
                             KEY_DELETE => {
                                 if !modifiers.is_empty() && !modifiers.is_shift() {
+                                    do_commit_word(handle, input_state.get_displaying_word());
                                     input_state.new_word();
                                 } else {
                                     input_state.pop();
@@ -206,10 +296,12 @@ fn event_handler(
                                     if c.is_numeric() {
                                         input_state.push(c);
                                     }
+                                    do_commit_word(handle, input_state.get_displaying_word());
                                     input_state.new_word();
                                 } else {
                                     // Otherwise, process the character
                                     if modifiers.is_super() || modifiers.is_alt() {
+                                        do_commit_word(handle, input_state.get_displaying_word());
                                         input_state.new_word();
                                     } else if input_state.is_tracking() {
                                         input_state.push(
@@ -274,6 +366,7 @@ fn main() {
         _ = app.launch(());
     } else {
         // Start the GõKey application
+        _ = EVENT_REWRITER.set(config::Config::load().event_rewriter());
         rebuild_keyboard_layout_map();
         let win = WindowDesc::new(ui::main_ui_builder())
             .title(app_title)
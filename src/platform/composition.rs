@@ -0,0 +1,83 @@
+//! Native macOS marked-text (preedit) composition.
+//!
+//! Instead of faking edits with backspace-then-retype keystrokes, we drive
+//! the focused view's `NSTextInputClient` directly: the in-progress
+//! Vietnamese word is shown as underlined "marked text" owned by the IME,
+//! and only becomes real document content when it is committed on a word
+//! boundary. This mirrors how Wayland's `text_input_v3` separates a preedit
+//! string from the committed text, and removes the need for per-app
+//! workarounds like re-sending a dismissed selection.
+
+use cocoa::appkit::NSApp;
+use cocoa::base::{id, nil};
+use cocoa::foundation::NSRange;
+use objc::{msg_send, sel, sel_impl};
+
+use super::Handle;
+
+/// A large, out-of-bounds range understood by `NSTextInputClient` to mean
+/// "replace whatever is currently marked" rather than a specific span.
+const REPLACE_CURRENT_MARKED_TEXT: NSRange = NSRange {
+    location: u64::MAX,
+    length: 0,
+};
+
+pub struct Composition;
+
+impl Composition {
+    /// Show `text` as preedit with the cursor placed at `cursor`, replacing
+    /// any marked text already in flight. Returns `false` if the focused
+    /// view does not support marked text, in which case the caller should
+    /// fall back to [`super::send_string`]/[`super::send_backspace`].
+    pub fn set_marked_text(_handle: Handle, text: &str, cursor: usize) -> bool {
+        let Some(client) = text_input_client() else {
+            return false;
+        };
+        unsafe {
+            let ns_text = cocoa::foundation::NSString::alloc(nil).init_str(text);
+            let selected_range = NSRange::new(cursor as u64, 0);
+            let _: () = msg_send![client,
+                setMarkedText: ns_text
+                selectedRange: selected_range
+                replacementRange: REPLACE_CURRENT_MARKED_TEXT
+            ];
+        }
+        true
+    }
+
+    /// Commit `text` to the document and end the current composition
+    /// session, replacing any marked text with it.
+    pub fn commit(_handle: Handle, text: &str) -> bool {
+        let Some(client) = text_input_client() else {
+            return false;
+        };
+        unsafe {
+            let ns_text = cocoa::foundation::NSString::alloc(nil).init_str(text);
+            let _: () = msg_send![client,
+                insertText: ns_text
+                replacementRange: REPLACE_CURRENT_MARKED_TEXT
+            ];
+        }
+        true
+    }
+
+    /// Drop any in-flight marked text without committing it, e.g. when the
+    /// word is abandoned or Escape is pressed.
+    pub fn clear(handle: Handle) -> bool {
+        Self::set_marked_text(handle, "", 0)
+    }
+}
+
+/// The key window's first responder, if it implements `NSTextInputClient`.
+fn text_input_client() -> Option<id> {
+    unsafe {
+        let window: id = msg_send![NSApp(), keyWindow];
+        if window.is_null() {
+            return None;
+        }
+        let responder: id = msg_send![window, firstResponder];
+        let selector = sel!(setMarkedText:selectedRange:replacementRange:);
+        let responds: bool = msg_send![responder, respondsToSelector: selector];
+        (!responder.is_null() && responds).then_some(responder)
+    }
+}
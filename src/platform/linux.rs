@@ -0,0 +1,602 @@
+//! Linux event tap and composition backend.
+//!
+//! On Wayland, key decoding and text commit both go through the same
+//! protocol object: `wl_keyboard` delivers keysyms (converted to Unicode via
+//! xkbcommon), and `zwp_text_input_v3` both commits text and exposes
+//! preedit-string / delete-surrounding-text, which is exactly the
+//! marked-text model [`super::Composition`] needs. Where no Wayland
+//! compositor is available we fall back to X11 (`XRecord`) with raw evdev
+//! keycodes for decoding and `XTestFakeKeyEvent` for synthesizing text.
+
+use std::os::fd::OwnedFd;
+use std::sync::{Mutex, OnceLock};
+
+use wayland_client::protocol::{wl_keyboard, wl_registry, wl_seat};
+use wayland_client::{Connection, Dispatch, EventQueue, QueueHandle, WEnum};
+use wayland_protocols::wp::text_input::zv3::client::{
+    zwp_text_input_manager_v3, zwp_text_input_v3,
+};
+use xkbcommon::xkb;
+
+use super::{EventTapType, KeyModifier, PhysicalKey, PressedKey};
+
+// evdev keycodes (linux/input-event-codes.h); there is no "Globe" key on
+// standard PC keyboards, so it's left unmapped.
+pub const RAW_KEY_GLOBE: u16 = 0;
+pub const RAW_ARROW_LEFT: u16 = 105;
+pub const RAW_ARROW_RIGHT: u16 = 106;
+pub const RAW_ARROW_UP: u16 = 103;
+pub const RAW_ARROW_DOWN: u16 = 108;
+
+/// `wl_keyboard`/evdev keycodes are offset by 8 from the X11/xkb keycodes
+/// `xkb::State` expects (the first 8 are reserved).
+const EVDEV_XKB_OFFSET: u32 = 8;
+
+/// Identifies which windowing system backend is driving the tap; injection
+/// calls need to know which one they're talking to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Handle {
+    Wayland,
+    X11,
+}
+
+pub fn ensure_accessibility_permission() -> bool {
+    // Neither Wayland nor X11 has a macOS-style Accessibility gate; input
+    // access is governed by session/seat permissions instead.
+    true
+}
+
+pub fn run_event_listener(
+    callback: &'static (dyn Fn(Handle, EventTapType, Option<PressedKey>, KeyModifier) -> bool
+          + Sync),
+) {
+    if wayland_session_available() {
+        run_wayland_event_listener(callback);
+    } else {
+        run_x11_event_listener(callback);
+    }
+}
+
+fn wayland_session_available() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").is_some()
+}
+
+type Callback =
+    &'static (dyn Fn(Handle, EventTapType, Option<PressedKey>, KeyModifier) -> bool + Sync);
+
+/// Shared with [`send_string`]/[`send_backspace`]/[`Composition`] so they can
+/// act on the `zwp_text_input_v3` object the event loop bound, instead of
+/// each call having to walk the registry again.
+static WAYLAND_TEXT_INPUT: OnceLock<Mutex<Option<zwp_text_input_v3::ZwpTextInputV3>>> =
+    OnceLock::new();
+
+fn wayland_text_input() -> &'static Mutex<Option<zwp_text_input_v3::ZwpTextInputV3>> {
+    WAYLAND_TEXT_INPUT.get_or_init(|| Mutex::new(None))
+}
+
+struct WaylandApp {
+    callback: Callback,
+    seat: Option<wl_seat::WlSeat>,
+    text_input_manager: Option<zwp_text_input_manager_v3::ZwpTextInputManagerV3>,
+    xkb_context: xkb::Context,
+    xkb_state: Option<xkb::State>,
+    modifiers: KeyModifier,
+}
+
+fn run_wayland_event_listener(callback: Callback) {
+    let Ok(conn) = Connection::connect_to_env() else {
+        log::error!("No Wayland compositor reachable; falling back to X11");
+        run_x11_event_listener(callback);
+        return;
+    };
+    let display = conn.display();
+    let mut event_queue: EventQueue<WaylandApp> = conn.new_event_queue();
+    let qh = event_queue.handle();
+    display.get_registry(&qh, ());
+
+    let mut app = WaylandApp {
+        callback,
+        seat: None,
+        text_input_manager: None,
+        xkb_context: xkb::Context::new(xkb::CONTEXT_NO_FLAGS),
+        xkb_state: None,
+        modifiers: KeyModifier::MODIFIER_NONE,
+    };
+
+    // Round-trip once so all globals (wl_seat, zwp_text_input_manager_v3)
+    // are bound before we ask for a keyboard/text-input object off them.
+    if event_queue.roundtrip(&mut app).is_err() {
+        log::error!("Wayland registry roundtrip failed; falling back to X11");
+        run_x11_event_listener(callback);
+        return;
+    }
+
+    if let (Some(seat), Some(manager)) = (&app.seat, &app.text_input_manager) {
+        let text_input = manager.get_text_input(seat, &qh, ());
+        *wayland_text_input().lock().unwrap() = Some(text_input);
+    } else {
+        log::warn!("Compositor has no wl_seat/zwp_text_input_manager_v3; composition disabled");
+    }
+
+    loop {
+        if event_queue.blocking_dispatch(&mut app).is_err() {
+            log::error!("Wayland connection lost");
+            break;
+        }
+    }
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for WaylandApp {
+    fn event(
+        app: &mut Self,
+        registry: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _data: &(),
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_registry::Event::Global { name, interface, version } = event {
+            match interface.as_str() {
+                "wl_seat" => {
+                    app.seat = Some(registry.bind(name, version.min(7), qh, ()));
+                }
+                "zwp_text_input_manager_v3" => {
+                    app.text_input_manager = Some(registry.bind(name, version.min(1), qh, ()));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for WaylandApp {
+    fn event(
+        app: &mut Self,
+        seat: &wl_seat::WlSeat,
+        event: wl_seat::Event,
+        _data: &(),
+        _conn: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_seat::Event::Capabilities { capabilities: WEnum::Value(caps) } = event {
+            if caps.contains(wl_seat::Capability::Keyboard) {
+                seat.get_keyboard(qh, ());
+            }
+        }
+        let _ = app;
+    }
+}
+
+impl Dispatch<wl_keyboard::WlKeyboard, ()> for WaylandApp {
+    fn event(
+        app: &mut Self,
+        _keyboard: &wl_keyboard::WlKeyboard,
+        event: wl_keyboard::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_keyboard::Event::Keymap { format, fd, size } => {
+                app.load_keymap(format, fd, size);
+            }
+            wl_keyboard::Event::Modifiers { mods_depressed, mods_latched, mods_locked, group, .. } => {
+                if let Some(state) = &mut app.xkb_state {
+                    state.update_mask(mods_depressed, mods_latched, mods_locked, 0, 0, group);
+                    app.modifiers = modifiers_from_xkb_state(state);
+                }
+            }
+            wl_keyboard::Event::Key { key, state: WEnum::Value(key_state), .. } => {
+                app.handle_key(key, key_state);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<zwp_text_input_manager_v3::ZwpTextInputManagerV3, ()> for WaylandApp {
+    fn event(
+        _app: &mut Self,
+        _proxy: &zwp_text_input_manager_v3::ZwpTextInputManagerV3,
+        _event: zwp_text_input_manager_v3::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // No events defined on the manager itself.
+    }
+}
+
+impl Dispatch<zwp_text_input_v3::ZwpTextInputV3, ()> for WaylandApp {
+    fn event(
+        _app: &mut Self,
+        text_input: &zwp_text_input_v3::ZwpTextInputV3,
+        event: zwp_text_input_v3::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        // Enabled each time the compositor reports focus entering a surface;
+        // the compositor just needs to know we exist so it routes
+        // preedit/commit requests through us.
+        if let zwp_text_input_v3::Event::Enter { .. } = event {
+            text_input.enable();
+            text_input.commit();
+        }
+    }
+}
+
+impl WaylandApp {
+    fn load_keymap(&mut self, format: WEnum<wl_keyboard::KeymapFormat>, fd: OwnedFd, size: u32) {
+        if format != WEnum::Value(wl_keyboard::KeymapFormat::XkbV1) {
+            return;
+        }
+        // SAFETY: `fd` is a compositor-owned memory-mapped keymap string of
+        // exactly `size` bytes, as required by the `wl_keyboard::keymap`
+        // event; it is not retained past this call.
+        let keymap = unsafe {
+            xkb::Keymap::new_from_fd(
+                &self.xkb_context,
+                fd,
+                size as usize,
+                xkb::KEYMAP_FORMAT_TEXT_V1,
+                xkb::KEYMAP_COMPILE_NO_FLAGS,
+            )
+        };
+        self.xkb_state = keymap.map(|keymap| xkb::State::new(&keymap));
+    }
+
+    fn handle_key(&mut self, evdev_keycode: u32, key_state: wl_keyboard::KeyState) {
+        if key_state != wl_keyboard::KeyState::Pressed {
+            return;
+        }
+        let physical = PhysicalKey(evdev_keycode as u16);
+        let pressed_key = match evdev_keycode as u16 {
+            RAW_ARROW_LEFT | RAW_ARROW_RIGHT | RAW_ARROW_UP | RAW_ARROW_DOWN => {
+                Some(PressedKey::Raw(evdev_keycode as u16))
+            }
+            _ => self.xkb_state.as_ref().and_then(|state| {
+                let keysym = state.key_get_one_sym(evdev_keycode + EVDEV_XKB_OFFSET);
+                keysym_to_pressed_char(keysym, physical)
+            }),
+        };
+        if pressed_key.is_some() {
+            (self.callback)(Handle::Wayland, EventTapType::KeyDown, pressed_key, self.modifiers);
+        }
+    }
+}
+
+/// Maps an xkb keysym to the `char` the rest of the crate expects, special-
+/// casing the handful of control keys it matches on by value (`KEY_DELETE`
+/// etc.) since xkbcommon's own Unicode translation of e.g. Backspace
+/// (`\u{8}`) doesn't match what macOS's event tap reports for the same key.
+fn keysym_to_pressed_char(keysym: xkb::Keysym, physical: PhysicalKey) -> Option<PressedKey> {
+    let text = match keysym {
+        xkb::KEY_BackSpace => super::KEY_DELETE,
+        xkb::KEY_Return | xkb::KEY_KP_Enter => super::KEY_ENTER,
+        xkb::KEY_Tab => super::KEY_TAB,
+        xkb::KEY_space => super::KEY_SPACE,
+        xkb::KEY_Escape => super::KEY_ESCAPE,
+        _ => xkb::keysym_to_utf8(keysym).chars().next()?,
+    };
+    Some(PressedKey::Char { physical, text })
+}
+
+fn modifiers_from_xkb_state(state: &xkb::State) -> KeyModifier {
+    let mut modifiers = KeyModifier::MODIFIER_NONE;
+    let active = |name: &str| state.mod_name_is_active(name, xkb::STATE_MODS_EFFECTIVE);
+    modifiers.set(KeyModifier::CONTROL, active(xkb::MOD_NAME_CTRL));
+    modifiers.set(KeyModifier::SHIFT, active(xkb::MOD_NAME_SHIFT));
+    modifiers.set(KeyModifier::ALT, active(xkb::MOD_NAME_ALT));
+    modifiers.set(KeyModifier::SUPER, active(xkb::MOD_NAME_LOGO));
+    modifiers.set(KeyModifier::CAPSLOCK, active(xkb::MOD_NAME_CAPS));
+    modifiers
+}
+
+pub fn send_string(handle: Handle, text: &str) -> Result<(), ()> {
+    match handle {
+        Handle::Wayland => {
+            // This fallback only matters for clients that never entered
+            // composition (see `Composition::commit`, which is what runs
+            // for the normal word-boundary path).
+            let guard = wayland_text_input().lock().unwrap();
+            let Some(text_input) = guard.as_ref() else {
+                return Err(());
+            };
+            text_input.commit_string(text.to_string());
+            text_input.commit();
+            Ok(())
+        }
+        Handle::X11 => x11::send_string(text),
+    }
+}
+
+pub fn send_backspace(handle: Handle, count: usize) -> Result<(), ()> {
+    match handle {
+        Handle::Wayland => {
+            let guard = wayland_text_input().lock().unwrap();
+            let Some(text_input) = guard.as_ref() else {
+                return Err(());
+            };
+            // `delete_surrounding_text` counts in bytes, while `count` is a
+            // character count, so words with multi-byte characters lose
+            // fewer bytes than characters. `main.rs` only falls back to this
+            // when `Composition` found no text-input object, in which case
+            // the early return above has already fired.
+            text_input.delete_surrounding_text(count as u32, 0);
+            text_input.commit();
+            Ok(())
+        }
+        Handle::X11 => x11::send_backspace(count),
+    }
+}
+
+pub fn add_app_change_callback(callback: impl Fn() + Send + 'static) {
+    // No active-window tracking is wired up on either Wayland or X11, so
+    // the callback is kept alive but never invoked, and
+    // `frontmost_app_bundle_id` always returns `None`.
+    std::mem::forget(Box::new(callback) as Box<dyn Fn() + Send>);
+}
+
+pub fn frontmost_app_bundle_id() -> Option<String> {
+    None
+}
+
+/// The character a standard US-QWERTY layout produces for `physical`,
+/// ignoring whatever xkb layout is actually active. `physical` carries the
+/// raw evdev keycode on Linux.
+pub fn us_qwerty_char(physical: PhysicalKey) -> Option<char> {
+    let keymap = xkb::Keymap::new_from_names(
+        &xkb::Context::new(xkb::CONTEXT_NO_FLAGS),
+        "",
+        "",
+        "us",
+        "",
+        None,
+        xkb::KEYMAP_COMPILE_NO_FLAGS,
+    )?;
+    let state = xkb::State::new(&keymap);
+    let keysym = state.key_get_one_sym(physical.0 as u32 + EVDEV_XKB_OFFSET);
+    xkb::keysym_to_utf8(keysym).chars().next()
+}
+
+/// Marked-text composition backed by `zwp_text_input_v3`, which exposes
+/// `set_preedit_string`/`commit_string` directly — no synthetic keystrokes
+/// needed on Wayland. The X11 fallback has no equivalent protocol, so it
+/// always reports unsupported and falls back to `send_string`/
+/// `send_backspace`, the same as any non-`NSTextInputClient` app on macOS.
+pub struct Composition;
+
+impl Composition {
+    pub fn set_marked_text(handle: Handle, text: &str, cursor: usize) -> bool {
+        let Handle::Wayland = handle else { return false };
+        let guard = wayland_text_input().lock().unwrap();
+        let Some(text_input) = guard.as_ref() else { return false };
+        let cursor = cursor as i32;
+        text_input.set_preedit_string(text.to_string(), cursor, cursor);
+        text_input.commit();
+        true
+    }
+
+    pub fn commit(handle: Handle, text: &str) -> bool {
+        let Handle::Wayland = handle else { return false };
+        let guard = wayland_text_input().lock().unwrap();
+        let Some(text_input) = guard.as_ref() else { return false };
+        text_input.set_preedit_string(String::new(), 0, 0);
+        text_input.commit_string(text.to_string());
+        text_input.commit();
+        true
+    }
+
+    /// Drop any in-flight marked text without committing it, e.g. when the
+    /// word is abandoned or Escape is pressed.
+    pub fn clear(handle: Handle) -> bool {
+        Self::set_marked_text(handle, "", 0)
+    }
+}
+
+/// X11 fallback: global key interception via `XRecord`, synthesis via
+/// `XTestFakeKeyEvent`. Declared by hand against Xlib/Xtst instead of
+/// pulling in a binding crate, the same way `macos.rs` hand-declares the
+/// one `AXIsProcessTrustedWithOptions` symbol it needs from `ApplicationServices`.
+mod x11 {
+    use std::ffi::{c_char, c_int, c_uchar, c_uint, c_ulong, c_void};
+    use std::ptr;
+
+    use super::{EventTapType, Handle, KeyModifier, PhysicalKey, PressedKey};
+
+    type Display = c_void;
+    type XPointer = *mut c_char;
+
+    const KEY_PRESS: c_int = 2;
+
+    #[repr(C)]
+    struct XRecordRange {
+        core_requests: XRecordRange8,
+        core_replies: XRecordRange8,
+        ext_requests: XRecordExtRange,
+        ext_replies: XRecordExtRange,
+        delivered_events: XRecordRange8,
+        device_events: XRecordRange8,
+        errors: XRecordRange8,
+        client_started: bool,
+        client_died: bool,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct XRecordRange8 {
+        first: c_uchar,
+        last: c_uchar,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct XRecordExtRange {
+        ext_major: XRecordRange8,
+        ext_minor: XRecordRange8,
+    }
+
+    #[repr(C)]
+    struct XRecordInterceptData {
+        id_base: c_ulong,
+        server_time: c_ulong,
+        client_seq: c_ulong,
+        category: c_int,
+        client_swapped: bool,
+        data: *const c_uchar,
+        data_len: c_ulong,
+    }
+
+    // evdev-to-X11 keycode offset: X11 keycodes are evdev + 8, same as xkb.
+    const EVDEV_X11_OFFSET: u16 = 8;
+
+    extern "C" {
+        fn XOpenDisplay(display_name: *const c_char) -> *mut Display;
+        fn XRecordAllocRange() -> *mut XRecordRange;
+        fn XRecordCreateContext(
+            display: *mut Display,
+            datum_flags: c_int,
+            clients: *mut c_ulong,
+            nclients: c_int,
+            ranges: *mut *mut XRecordRange,
+            nranges: c_int,
+        ) -> c_ulong;
+        fn XRecordEnableContext(
+            display: *mut Display,
+            context: c_ulong,
+            callback: extern "C" fn(XPointer, *mut XRecordInterceptData),
+            closure: XPointer,
+        ) -> c_int;
+        fn XRecordFreeData(data: *mut XRecordInterceptData);
+        fn XTestFakeKeyEvent(display: *mut Display, keycode: c_uint, is_press: c_int, delay: c_ulong);
+        fn XKeysymToKeycode(display: *mut Display, keysym: c_ulong) -> c_uchar;
+        fn XStringToKeysym(string: *const c_char) -> c_ulong;
+        fn XFlush(display: *mut Display);
+    }
+
+    const XRECORD_FROM_SERVER: c_int = 0;
+    const XRECORD_CURRENT_CLIENTS: c_ulong = 1;
+
+    static mut CALLBACK: Option<
+        &'static (dyn Fn(Handle, EventTapType, Option<PressedKey>, KeyModifier) -> bool + Sync),
+    > = None;
+    static mut KEYBOARD_DISPLAY: *mut Display = ptr::null_mut();
+
+    pub fn run_event_listener(
+        callback: &'static (dyn Fn(Handle, EventTapType, Option<PressedKey>, KeyModifier) -> bool
+              + Sync),
+    ) {
+        unsafe {
+            let control_display = XOpenDisplay(ptr::null());
+            let data_display = XOpenDisplay(ptr::null());
+            if control_display.is_null() || data_display.is_null() {
+                log::error!("Failed to open an X11 display; no $DISPLAY?");
+                return;
+            }
+            CALLBACK = Some(callback);
+            KEYBOARD_DISPLAY = data_display;
+
+            let mut range = XRecordAllocRange();
+            if range.is_null() {
+                log::error!("XRecordAllocRange failed");
+                return;
+            }
+            (*range).device_events = XRecordRange8 { first: KEY_PRESS as c_uchar, last: KEY_PRESS as c_uchar };
+            let mut clients = [XRECORD_CURRENT_CLIENTS];
+            let context = XRecordCreateContext(
+                control_display,
+                0,
+                clients.as_mut_ptr(),
+                1,
+                &mut range,
+                1,
+            );
+            if context == 0 {
+                log::error!("XRecordCreateContext failed; is the Record extension enabled?");
+                return;
+            }
+            // Blocks the calling thread forever, delivering every key press
+            // on the data display to `record_callback`.
+            XRecordEnableContext(data_display, context, record_callback, ptr::null_mut());
+        }
+    }
+
+    extern "C" fn record_callback(_closure: XPointer, data: *mut XRecordInterceptData) {
+        unsafe {
+            if data.is_null() || (*data).category != XRECORD_FROM_SERVER {
+                XRecordFreeData(data);
+                return;
+            }
+            // Wire format: 1 byte event type, 1 byte detail (keycode), then
+            // the rest of an XKeyEvent's fixed fields.
+            let payload = (*data).data;
+            if payload.is_null() || (*data).data_len < 2 {
+                XRecordFreeData(data);
+                return;
+            }
+            let event_code = *payload as c_int;
+            let keycode = *payload.add(1) as u16;
+            XRecordFreeData(data);
+
+            if event_code != KEY_PRESS {
+                return;
+            }
+            if let Some(callback) = CALLBACK {
+                let physical = PhysicalKey(keycode.saturating_sub(EVDEV_X11_OFFSET));
+                let pressed_key = super::us_qwerty_char(physical)
+                    .map(|text| PressedKey::Char { physical, text });
+                callback(Handle::X11, EventTapType::KeyDown, pressed_key, KeyModifier::MODIFIER_NONE);
+            }
+        }
+    }
+
+    pub fn send_string(text: &str) -> Result<(), ()> {
+        unsafe {
+            if KEYBOARD_DISPLAY.is_null() {
+                return Err(());
+            }
+            for ch in text.chars() {
+                let name = format!("U{:04X}\0", ch as u32);
+                let keysym = XStringToKeysym(name.as_ptr() as *const c_char);
+                if keysym == 0 {
+                    continue;
+                }
+                let keycode = XKeysymToKeycode(KEYBOARD_DISPLAY, keysym);
+                if keycode == 0 {
+                    continue;
+                }
+                XTestFakeKeyEvent(KEYBOARD_DISPLAY, keycode as c_uint, 1, 0);
+                XTestFakeKeyEvent(KEYBOARD_DISPLAY, keycode as c_uint, 0, 0);
+            }
+            XFlush(KEYBOARD_DISPLAY);
+        }
+        Ok(())
+    }
+
+    pub fn send_backspace(count: usize) -> Result<(), ()> {
+        unsafe {
+            if KEYBOARD_DISPLAY.is_null() {
+                return Err(());
+            }
+            let keysym = XStringToKeysym(b"BackSpace\0".as_ptr() as *const c_char);
+            let keycode = XKeysymToKeycode(KEYBOARD_DISPLAY, keysym);
+            if keycode == 0 {
+                return Err(());
+            }
+            for _ in 0..count {
+                XTestFakeKeyEvent(KEYBOARD_DISPLAY, keycode as c_uint, 1, 0);
+                XTestFakeKeyEvent(KEYBOARD_DISPLAY, keycode as c_uint, 0, 0);
+            }
+            XFlush(KEYBOARD_DISPLAY);
+        }
+        Ok(())
+    }
+}
+
+fn run_x11_event_listener(
+    callback: &'static (dyn Fn(Handle, EventTapType, Option<PressedKey>, KeyModifier) -> bool
+          + Sync),
+) {
+    x11::run_event_listener(callback);
+}
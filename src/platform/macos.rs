@@ -0,0 +1,216 @@
+//! macOS event tap: listens for key events system-wide and synthesizes
+//! output text back into whichever app currently has focus.
+
+use std::ffi::c_void;
+
+use core_foundation::base::TCFType;
+use core_foundation::runloop::{kCFRunLoopCommonModes, CFRunLoop};
+use core_graphics::event::{
+    CGEvent, CGEventFlags, CGEventTapLocation, CGEventTapOptions, CGEventTapPlacement,
+    CGEventTapProxy, CGEventType, EventField,
+};
+use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
+
+use super::{EventTapType, KeyModifier, PhysicalKey, PressedKey};
+
+pub const RAW_KEY_GLOBE: u16 = 0xb2;
+pub const RAW_ARROW_LEFT: u16 = 0x7b;
+pub const RAW_ARROW_RIGHT: u16 = 0x7c;
+pub const RAW_ARROW_DOWN: u16 = 0x7d;
+pub const RAW_ARROW_UP: u16 = 0x7e;
+
+/// Opaque handle identifying the event tap that produced an event, so a
+/// synthesized event can be injected back into the same session.
+pub type Handle = CGEventTapProxy;
+
+pub fn ensure_accessibility_permission() -> bool {
+    unsafe { AXIsProcessTrustedWithOptions(std::ptr::null()) }
+}
+
+pub fn run_event_listener(
+    callback: &'static (dyn Fn(Handle, EventTapType, Option<PressedKey>, KeyModifier) -> bool
+          + Sync),
+) {
+    let Some(tap) = core_graphics::event::CGEventTap::new(
+        CGEventTapLocation::Session,
+        CGEventTapPlacement::HeadInsertEventTap,
+        CGEventTapOptions::Default,
+        vec![CGEventType::KeyDown, CGEventType::FlagsChanged],
+        |proxy, event_type, event| handle_cg_event(proxy, event_type, event, callback),
+    ) else {
+        log::error!("Failed to create the event tap. Is Accessibility permission granted?");
+        return;
+    };
+
+    let run_loop = CFRunLoop::get_current();
+    let loop_source = tap
+        .mach_port
+        .create_runloop_source(0)
+        .expect("Failed to create a run loop source for the event tap");
+    unsafe {
+        run_loop.add_source(&loop_source, kCFRunLoopCommonModes);
+    }
+    tap.enable();
+    CFRunLoop::run_current();
+}
+
+fn handle_cg_event(
+    proxy: CGEventTapProxy,
+    event_type: CGEventType,
+    event: &CGEvent,
+    callback: &(dyn Fn(Handle, EventTapType, Option<PressedKey>, KeyModifier) -> bool + Sync),
+) -> Option<CGEvent> {
+    let modifiers = modifiers_from_flags(event.get_flags());
+    let tap_type = match event_type {
+        CGEventType::FlagsChanged => EventTapType::FlagsChanged,
+        CGEventType::KeyDown => EventTapType::KeyDown,
+        _ => EventTapType::Other,
+    };
+    let pressed_key = pressed_key_from_event(event_type, event);
+
+    let handled = callback(proxy, tap_type, pressed_key, modifiers);
+    if handled {
+        None
+    } else {
+        Some(event.clone())
+    }
+}
+
+fn pressed_key_from_event(event_type: CGEventType, event: &CGEvent) -> Option<PressedKey> {
+    if event_type != CGEventType::KeyDown {
+        return None;
+    }
+    let keycode = event.get_integer_value_field(EventField::KEYBOARD_EVENT_KEYCODE) as u16;
+    if keycode == RAW_KEY_GLOBE
+        || keycode == RAW_ARROW_UP
+        || keycode == RAW_ARROW_DOWN
+        || keycode == RAW_ARROW_LEFT
+        || keycode == RAW_ARROW_RIGHT
+    {
+        return Some(PressedKey::Raw(keycode));
+    }
+    let text = event.get_unicode_string().chars().next()?;
+    Some(PressedKey::Char {
+        physical: PhysicalKey(keycode),
+        text,
+    })
+}
+
+/// The character a standard US-QWERTY layout produces for `physical`,
+/// ignoring whatever layout is actually active. Used when the user opts
+/// into "US-QWERTY positions" typing so Telex/VNI rules stay on the same
+/// physical keys regardless of the system keyboard layout.
+pub fn us_qwerty_char(physical: PhysicalKey) -> Option<char> {
+    // macOS virtual keycodes for the letter/number row, independent of the
+    // active layout.
+    Some(match physical.0 {
+        0x00 => 'a',
+        0x0b => 'b',
+        0x08 => 'c',
+        0x02 => 'd',
+        0x0e => 'e',
+        0x03 => 'f',
+        0x05 => 'g',
+        0x04 => 'h',
+        0x22 => 'i',
+        0x26 => 'j',
+        0x28 => 'k',
+        0x25 => 'l',
+        0x2e => 'm',
+        0x2d => 'n',
+        0x1f => 'o',
+        0x23 => 'p',
+        0x0c => 'q',
+        0x0f => 'r',
+        0x01 => 's',
+        0x11 => 't',
+        0x20 => 'u',
+        0x09 => 'v',
+        0x0d => 'w',
+        0x07 => 'x',
+        0x10 => 'y',
+        0x06 => 'z',
+        0x1d => '0',
+        0x12 => '1',
+        0x13 => '2',
+        0x14 => '3',
+        0x15 => '4',
+        0x17 => '5',
+        0x16 => '6',
+        0x1a => '7',
+        0x1c => '8',
+        0x19 => '9',
+        _ => return None,
+    })
+}
+
+fn modifiers_from_flags(flags: CGEventFlags) -> KeyModifier {
+    let mut modifiers = KeyModifier::MODIFIER_NONE;
+    modifiers.set(KeyModifier::CONTROL, flags.contains(CGEventFlags::CGEventFlagControl));
+    modifiers.set(KeyModifier::SHIFT, flags.contains(CGEventFlags::CGEventFlagShift));
+    modifiers.set(KeyModifier::ALT, flags.contains(CGEventFlags::CGEventFlagAlternate));
+    modifiers.set(KeyModifier::SUPER, flags.contains(CGEventFlags::CGEventFlagCommand));
+    modifiers.set(KeyModifier::CAPSLOCK, flags.contains(CGEventFlags::CGEventFlagAlphaShift));
+    modifiers
+}
+
+/// Synthesize `text` as a sequence of Unicode key-down/key-up events.
+///
+/// This remains as a fallback for apps whose focused view does not respond
+/// to `NSTextInputClient` (see [`super::Composition`]), where we have no
+/// choice but to fake keystrokes.
+pub fn send_string(handle: Handle, text: &str) -> Result<(), ()> {
+    let source = CGEventSource::new(CGEventSourceStateID::CombinedSessionState).map_err(|_| ())?;
+    let event = CGEvent::new_keyboard_event(source, 0, true).map_err(|_| ())?;
+    let utf16: Vec<u16> = text.encode_utf16().collect();
+    event.set_string_from_utf16_unchecked(&utf16);
+    event.post_to_pid(handle_pid(handle));
+    Ok(())
+}
+
+pub fn send_backspace(handle: Handle, count: usize) -> Result<(), ()> {
+    const KEY_CODE_DELETE: i64 = 0x33;
+    let source = CGEventSource::new(CGEventSourceStateID::CombinedSessionState).map_err(|_| ())?;
+    for _ in 0..count {
+        let key_down = CGEvent::new_keyboard_event(source.clone(), KEY_CODE_DELETE as u16, true)
+            .map_err(|_| ())?;
+        let key_up = CGEvent::new_keyboard_event(source.clone(), KEY_CODE_DELETE as u16, false)
+            .map_err(|_| ())?;
+        key_down.post_to_pid(handle_pid(handle));
+        key_up.post_to_pid(handle_pid(handle));
+    }
+    Ok(())
+}
+
+/// The pid an event should be posted to; `0` lets CoreGraphics route it to
+/// whichever process currently owns the session the tap observed.
+fn handle_pid(_handle: Handle) -> u32 {
+    0
+}
+
+pub fn add_app_change_callback(callback: impl Fn() + Send + 'static) {
+    unsafe {
+        APP_CHANGE_CALLBACKS
+            .get_or_insert_with(Vec::new)
+            .push(Box::new(callback));
+    }
+    // `install_app_change_observer` registers no `NSWorkspace` observer, so
+    // the stored callbacks are never invoked.
+    install_app_change_observer();
+}
+
+static mut APP_CHANGE_CALLBACKS: Option<Vec<Box<dyn Fn() + Send>>> = None;
+
+/// The bundle identifier of the frontmost app. Always `None`: the frontmost
+/// app is not queried.
+pub fn frontmost_app_bundle_id() -> Option<String> {
+    None
+}
+
+fn install_app_change_observer() {
+    // Does nothing: no `NSWorkspace` notification observer is registered.
+}
+
+extern "C" {
+    fn AXIsProcessTrustedWithOptions(options: *const c_void) -> bool;
+}
@@ -0,0 +1,94 @@
+//! OS integration: the global keyboard event tap, synthesizing text into the
+//! focused app, and driving the native IME composition APIs.
+//!
+//! Everything outside this module only sees the small surface re-exported
+//! here, so the rest of the crate stays portable across the macOS and Linux
+//! backends below.
+
+#[cfg(target_os = "macos")]
+mod composition;
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(target_os = "linux")]
+mod linux;
+
+#[cfg(target_os = "macos")]
+pub use composition::Composition;
+#[cfg(target_os = "macos")]
+pub use macos::{
+    add_app_change_callback, ensure_accessibility_permission, frontmost_app_bundle_id,
+    run_event_listener, send_backspace, send_string, us_qwerty_char, Handle, RAW_ARROW_DOWN,
+    RAW_ARROW_LEFT, RAW_ARROW_RIGHT, RAW_ARROW_UP, RAW_KEY_GLOBE,
+};
+
+#[cfg(target_os = "linux")]
+pub use linux::{
+    add_app_change_callback, ensure_accessibility_permission, frontmost_app_bundle_id,
+    run_event_listener, send_backspace, send_string, us_qwerty_char, Composition, Handle,
+    RAW_ARROW_DOWN, RAW_ARROW_LEFT, RAW_ARROW_RIGHT, RAW_ARROW_UP, RAW_KEY_GLOBE,
+};
+
+use bitflags::bitflags;
+
+bitflags! {
+    #[derive(Default)]
+    pub struct KeyModifier: u32 {
+        const MODIFIER_NONE = 0;
+        const CONTROL       = 1 << 0;
+        const SHIFT         = 1 << 1;
+        const ALT           = 1 << 2;
+        const SUPER         = 1 << 3;
+        const CAPSLOCK      = 1 << 4;
+    }
+}
+
+impl KeyModifier {
+    pub fn is_control(&self) -> bool {
+        self.contains(Self::CONTROL)
+    }
+
+    pub fn is_shift(&self) -> bool {
+        self.contains(Self::SHIFT)
+    }
+
+    pub fn is_alt(&self) -> bool {
+        self.contains(Self::ALT)
+    }
+
+    pub fn is_super(&self) -> bool {
+        self.contains(Self::SUPER)
+    }
+
+    pub fn is_capslock(&self) -> bool {
+        self.contains(Self::CAPSLOCK)
+    }
+}
+
+/// A hardware key position (scancode), independent of the active keyboard
+/// layout. Two layouts can map the same `PhysicalKey` to different
+/// characters (e.g. the key left of "Z" is "W" on QWERTY but "A" on AZERTY).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PhysicalKey(pub u16);
+
+/// A key reported by the event tap: either a printable character, reported
+/// together with the physical position that produced it so typing rules can
+/// be matched independently of the active keyboard layout, or a raw keycode
+/// with no character representation (e.g. the Globe/fn key).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PressedKey {
+    Char { physical: PhysicalKey, text: char },
+    Raw(u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventTapType {
+    KeyDown,
+    FlagsChanged,
+    Other,
+}
+
+pub const KEY_ENTER: char = '\r';
+pub const KEY_TAB: char = '\t';
+pub const KEY_SPACE: char = ' ';
+pub const KEY_ESCAPE: char = '\u{1b}';
+pub const KEY_DELETE: char = '\u{7f}';
@@ -0,0 +1,291 @@
+//! Pre-processes raw key events before they reach the IME state machine:
+//! remaps keys/modifiers per a configurable table, and supports "sticky"
+//! modifiers so a hotkey can be composed one key at a time instead of held
+//! as a chord. Modeled on Chrome OS's `EventRewriter`.
+//!
+//! `event_handler` runs this first and uses whatever `(PressedKey,
+//! KeyModifier)` pair comes out instead of the raw event it received from
+//! the tap. No rule swallows an event.
+
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::platform::{KeyModifier, PressedKey};
+
+/// A single named modifier, used so remap rules can be written/stored
+/// without depending on `KeyModifier`'s bit layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ModifierName {
+    Control,
+    Shift,
+    Alt,
+    Super,
+    CapsLock,
+}
+
+impl ModifierName {
+    fn as_flag(self) -> KeyModifier {
+        match self {
+            ModifierName::Control => KeyModifier::CONTROL,
+            ModifierName::Shift => KeyModifier::SHIFT,
+            ModifierName::Alt => KeyModifier::ALT,
+            ModifierName::Super => KeyModifier::SUPER,
+            ModifierName::CapsLock => KeyModifier::CAPSLOCK,
+        }
+    }
+}
+
+/// One entry of the rewrite table.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum RewriteRule {
+    /// Whenever `from` is held, treat it as `to` instead (e.g. swap Control
+    /// and Command).
+    SwapModifiers { from: ModifierName, to: ModifierName },
+    /// Whenever the raw keycode `from` is pressed, treat it as `to` instead
+    /// (e.g. remap the Globe key to whatever raw key the hotkey trigger
+    /// uses). Modifier keys such as Caps Lock need `RemapModifierEdge`.
+    RemapRawKey { from: u16, to: u16 },
+    /// Whenever `modifier` transitions from released to held, synthesize a
+    /// press of the raw keycode `to` instead and drop `modifier` from the
+    /// reported state. Unlike `RemapRawKey`, this is how Caps Lock (which
+    /// never arrives as a `PressedKey::Raw` — only as a bit in `KeyModifier`
+    /// on a `FlagsChanged` event) can be remapped to e.g. `RAW_KEY_GLOBE` to
+    /// act as a toggle-Vietnamese key.
+    RemapModifierEdge { modifier: ModifierName, to: u16 },
+}
+
+/// Remaps keys/modifiers per a configurable table, with an optional
+/// "sticky modifiers" mode that latches a modifier from one keypress onto
+/// the next keystroke instead of requiring it to be held down.
+pub struct EventRewriter {
+    rules: Vec<RewriteRule>,
+    sticky_modifiers_enabled: bool,
+    latched_modifier: Mutex<KeyModifier>,
+    previous_modifiers: Mutex<KeyModifier>,
+}
+
+impl EventRewriter {
+    pub fn new(rules: Vec<RewriteRule>, sticky_modifiers_enabled: bool) -> Self {
+        Self {
+            rules,
+            sticky_modifiers_enabled,
+            latched_modifier: Mutex::new(KeyModifier::MODIFIER_NONE),
+            previous_modifiers: Mutex::new(KeyModifier::MODIFIER_NONE),
+        }
+    }
+
+    /// Rewrites an incoming event. `pressed_key` comes out `None` only when
+    /// it went in `None` (a modifier-only event); no rule swallows an event.
+    pub fn rewrite(
+        &self,
+        pressed_key: Option<PressedKey>,
+        modifiers: KeyModifier,
+    ) -> (Option<PressedKey>, KeyModifier) {
+        let previously_held = {
+            let mut previous = self.previous_modifiers.lock().unwrap();
+            let previously_held = *previous;
+            if pressed_key.is_none() {
+                *previous = modifiers;
+            }
+            previously_held
+        };
+
+        let mut modifiers = modifiers;
+        let mut pressed_key = pressed_key;
+
+        for rule in &self.rules {
+            match *rule {
+                RewriteRule::SwapModifiers { from, to } => {
+                    let (from, to) = (from.as_flag(), to.as_flag());
+                    let had_from = modifiers.contains(from);
+                    let had_to = modifiers.contains(to);
+                    modifiers.set(from, had_to);
+                    modifiers.set(to, had_from);
+                }
+                RewriteRule::RemapRawKey { from, to } => {
+                    if let Some(PressedKey::Raw(code)) = pressed_key {
+                        if code == from {
+                            pressed_key = Some(PressedKey::Raw(to));
+                        }
+                    }
+                }
+                RewriteRule::RemapModifierEdge { modifier, to } => {
+                    let flag = modifier.as_flag();
+                    if pressed_key.is_none() && modifiers.contains(flag) && !previously_held.contains(flag)
+                    {
+                        pressed_key = Some(PressedKey::Raw(to));
+                        modifiers.remove(flag);
+                    }
+                }
+            }
+        }
+
+        if self.sticky_modifiers_enabled {
+            modifiers = self.apply_sticky_modifiers(pressed_key, modifiers);
+        }
+
+        (pressed_key, modifiers)
+    }
+
+    /// Latches a lone modifier chord (no key pressed alongside it) onto the
+    /// next keystroke, so e.g. tapping Control then pressing Z is treated
+    /// like Control+Z held together.
+    fn apply_sticky_modifiers(
+        &self,
+        pressed_key: Option<PressedKey>,
+        modifiers: KeyModifier,
+    ) -> KeyModifier {
+        let mut latched = self.latched_modifier.lock().unwrap();
+        match pressed_key {
+            None if !modifiers.is_empty() => {
+                *latched = modifiers;
+                modifiers
+            }
+            None => modifiers,
+            Some(_) => {
+                let combined = modifiers | *latched;
+                *latched = KeyModifier::MODIFIER_NONE;
+                combined
+            }
+        }
+    }
+}
+
+impl Default for EventRewriter {
+    fn default() -> Self {
+        Self::new(Vec::new(), false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_rules_passes_events_through_unchanged() {
+        let rewriter = EventRewriter::default();
+        let key = Some(PressedKey::Char { physical: crate::platform::PhysicalKey(0), text: 'a' });
+        assert_eq!(rewriter.rewrite(key, KeyModifier::CONTROL), (key, KeyModifier::CONTROL));
+    }
+
+    #[test]
+    fn swap_modifiers_swaps_both_directions() {
+        let rewriter = EventRewriter::new(
+            vec![RewriteRule::SwapModifiers { from: ModifierName::Control, to: ModifierName::Super }],
+            false,
+        );
+        let (_, modifiers) = rewriter.rewrite(None, KeyModifier::CONTROL);
+        assert_eq!(modifiers, KeyModifier::SUPER);
+
+        let (_, modifiers) = rewriter.rewrite(None, KeyModifier::SUPER);
+        assert_eq!(modifiers, KeyModifier::CONTROL);
+    }
+
+    #[test]
+    fn swap_modifiers_leaves_unrelated_bits_alone() {
+        let rewriter = EventRewriter::new(
+            vec![RewriteRule::SwapModifiers { from: ModifierName::Control, to: ModifierName::Super }],
+            false,
+        );
+        let (_, modifiers) = rewriter.rewrite(None, KeyModifier::CONTROL | KeyModifier::SHIFT);
+        assert_eq!(modifiers, KeyModifier::SUPER | KeyModifier::SHIFT);
+    }
+
+    #[test]
+    fn remap_raw_key_rewrites_matching_code() {
+        let rewriter = EventRewriter::new(vec![RewriteRule::RemapRawKey { from: 1, to: 2 }], false);
+        let (key, _) = rewriter.rewrite(Some(PressedKey::Raw(1)), KeyModifier::MODIFIER_NONE);
+        assert_eq!(key, Some(PressedKey::Raw(2)));
+    }
+
+    #[test]
+    fn remap_raw_key_ignores_other_codes() {
+        let rewriter = EventRewriter::new(vec![RewriteRule::RemapRawKey { from: 1, to: 2 }], false);
+        let (key, _) = rewriter.rewrite(Some(PressedKey::Raw(3)), KeyModifier::MODIFIER_NONE);
+        assert_eq!(key, Some(PressedKey::Raw(3)));
+    }
+
+    #[test]
+    fn remap_modifier_edge_fires_once_on_press_and_drops_the_modifier() {
+        let rewriter = EventRewriter::new(
+            vec![RewriteRule::RemapModifierEdge { modifier: ModifierName::CapsLock, to: 42 }],
+            false,
+        );
+
+        // Rising edge: Caps Lock goes from not-held to held.
+        let (key, modifiers) = rewriter.rewrite(None, KeyModifier::CAPSLOCK);
+        assert_eq!(key, Some(PressedKey::Raw(42)));
+        assert!(!modifiers.contains(KeyModifier::CAPSLOCK));
+
+        // Still held on the next FlagsChanged: must not fire again.
+        let (key, _) = rewriter.rewrite(None, KeyModifier::CAPSLOCK);
+        assert_eq!(key, None);
+    }
+
+    #[test]
+    fn remap_modifier_edge_rearms_after_release() {
+        let rewriter = EventRewriter::new(
+            vec![RewriteRule::RemapModifierEdge { modifier: ModifierName::CapsLock, to: 42 }],
+            false,
+        );
+
+        let (key, _) = rewriter.rewrite(None, KeyModifier::CAPSLOCK);
+        assert_eq!(key, Some(PressedKey::Raw(42)));
+
+        let (key, _) = rewriter.rewrite(None, KeyModifier::MODIFIER_NONE);
+        assert_eq!(key, None);
+
+        let (key, _) = rewriter.rewrite(None, KeyModifier::CAPSLOCK);
+        assert_eq!(key, Some(PressedKey::Raw(42)));
+    }
+
+    #[test]
+    fn remap_modifier_edge_ignores_events_with_a_pressed_key() {
+        let rewriter = EventRewriter::new(
+            vec![RewriteRule::RemapModifierEdge { modifier: ModifierName::CapsLock, to: 42 }],
+            false,
+        );
+        let key = Some(PressedKey::Char { physical: crate::platform::PhysicalKey(0), text: 'a' });
+        let (key, modifiers) = rewriter.rewrite(key, KeyModifier::CAPSLOCK);
+        assert_ne!(key, Some(PressedKey::Raw(42)));
+        assert!(modifiers.contains(KeyModifier::CAPSLOCK));
+    }
+
+    #[test]
+    fn sticky_modifiers_latch_a_lone_chord_onto_the_next_keystroke() {
+        let rewriter = EventRewriter::new(Vec::new(), true);
+
+        // Control tapped alone (no key alongside it) latches.
+        let (_, modifiers) = rewriter.rewrite(None, KeyModifier::CONTROL);
+        assert_eq!(modifiers, KeyModifier::CONTROL);
+
+        // Modifiers released before the next key; latch should still apply.
+        let key = Some(PressedKey::Char { physical: crate::platform::PhysicalKey(0), text: 'z' });
+        let (_, modifiers) = rewriter.rewrite(key, KeyModifier::MODIFIER_NONE);
+        assert_eq!(modifiers, KeyModifier::CONTROL);
+    }
+
+    #[test]
+    fn sticky_modifiers_are_consumed_after_one_keystroke() {
+        let rewriter = EventRewriter::new(Vec::new(), true);
+
+        rewriter.rewrite(None, KeyModifier::CONTROL);
+        let key = Some(PressedKey::Char { physical: crate::platform::PhysicalKey(0), text: 'z' });
+        rewriter.rewrite(key, KeyModifier::MODIFIER_NONE);
+
+        // The latch was consumed by the keystroke above, so a second one
+        // shouldn't still carry Control.
+        let (_, modifiers) = rewriter.rewrite(key, KeyModifier::MODIFIER_NONE);
+        assert_eq!(modifiers, KeyModifier::MODIFIER_NONE);
+    }
+
+    #[test]
+    fn sticky_modifiers_disabled_does_not_latch() {
+        let rewriter = EventRewriter::new(Vec::new(), false);
+        rewriter.rewrite(None, KeyModifier::CONTROL);
+        let key = Some(PressedKey::Char { physical: crate::platform::PhysicalKey(0), text: 'z' });
+        let (_, modifiers) = rewriter.rewrite(key, KeyModifier::MODIFIER_NONE);
+        assert_eq!(modifiers, KeyModifier::MODIFIER_NONE);
+    }
+}